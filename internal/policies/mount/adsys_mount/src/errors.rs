@@ -0,0 +1,24 @@
+use std::fmt;
+
+/// Errors that can occur while parsing the mounts file or performing mount operations.
+#[derive(Debug)]
+pub enum AdsysMountError {
+    /// The mounts file or one of its entries could not be parsed. Carries the offending option
+    /// token or line, or the I/O error message when the file itself could not be read.
+    ParseError(String),
+    /// One or more mount operations failed.
+    MountError,
+}
+
+impl fmt::Display for AdsysMountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdsysMountError::ParseError(token) => {
+                write!(f, "failed to parse mounts file entry: {}", token)
+            }
+            AdsysMountError::MountError => write!(f, "one or more mount operations failed"),
+        }
+    }
+}
+
+impl std::error::Error for AdsysMountError {}
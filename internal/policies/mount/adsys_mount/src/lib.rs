@@ -1,23 +1,45 @@
 use gio::{
     self,
-    traits::{FileExt, MountOperationExt},
+    traits::{FileExt, MountExt, MountOperationExt},
 };
 use glib::ObjectExt;
 use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use std::{
+    cell::Cell,
     fs,
-    sync::{Arc, Mutex},
+    io::Read,
+    os::unix::io::FromRawFd,
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
 };
 
 mod errors;
 pub use errors::AdsysMountError;
 
+mod session;
+pub use session::{handle_user_mounts_daemon, MountSession};
+
+/// Timeout applied to a mount or unmount operation when its entry does not configure one.
+const DEFAULT_MOUNT_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// Represents a mount point read from the mounts file.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 struct MountEntry {
     mount_path: String,
+    options: MountOptions,
+}
+
+/// Per-entry options parsed from the bracketed prefix of a mounts file line,
+/// e.g. `[anonymous,readonly,timeout=30] smb://server/share`.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+struct MountOptions {
     is_anonymous: bool,
+    read_only: bool,
+    timeout: Option<Duration>,
+    username: Option<String>,
+    domain: Option<String>,
 }
 
 /// Struct representing the message that is to be passed in the glib channel.
@@ -36,26 +58,78 @@ pub enum MountStatus {
     Asked,
 }
 
-struct MountError {
-    path: String,
-    error: glib::Error,
+/// The terminal status of a single mounts-file entry after a mount or unmount attempt.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum MountOutcome {
+    /// The entry was mounted (or unmounted) successfully.
+    Mounted,
+    /// The entry was already in the desired state; not treated as a failure.
+    AlreadyMounted,
+    /// The operation failed; carries the underlying error message.
+    Failed(String),
+    /// The operation was cancelled after its configured timeout elapsed.
+    TimedOut,
+}
+
+/// Per-entry result of a [`handle_user_mounts`]/[`handle_user_unmounts`] run.
+#[derive(Debug)]
+pub struct MountReport {
+    pub path: String,
+    pub outcome: MountOutcome,
+}
+
+impl MountReport {
+    /// Whether this entry ended up in a state that should not be treated as a failure.
+    pub fn is_ok(&self) -> bool {
+        matches!(
+            self.outcome,
+            MountOutcome::Mounted | MountOutcome::AlreadyMounted
+        )
+    }
+}
+
+/// Reduces a report set down to a single pass/fail result, for callers that only care whether
+/// every entry succeeded and not which ones.
+pub fn summarize(reports: &[MountReport]) -> Result<(), AdsysMountError> {
+    if reports.iter().all(MountReport::is_ok) {
+        Ok(())
+    } else {
+        Err(AdsysMountError::MountError)
+    }
 }
 
 fn user_mount_cb(
     msg: Msg,
-    errors: &Mutex<Vec<MountError>>,
+    ignorable: gio::IOErrorEnum,
+    reports: &Mutex<Vec<MountReport>>,
     main_loop: &glib::MainLoop,
     mounts_left: &mut usize,
 ) -> glib::Continue {
     let Msg { path, status } = msg;
-    match status {
+    let outcome = match status {
+        Ok(MountStatus::Done) => {
+            debug!("Operation on {} was successful", path);
+            MountOutcome::Mounted
+        }
+        Err(error) if error.matches(ignorable) => {
+            debug!("{} was already in the desired state", path);
+            MountOutcome::AlreadyMounted
+        }
+        Err(error) if error.matches(gio::IOErrorEnum::Cancelled) => {
+            warn!("Operation for {} timed out", path);
+            MountOutcome::TimedOut
+        }
         Err(error) => {
-            warn!("Failed when mounting {}", path);
-            errors.lock().unwrap().push(MountError { path, error });
+            warn!("Operation on {} failed: {}", path, error);
+            MountOutcome::Failed(error.to_string())
+        }
+        Ok(other) => {
+            error!("Unexpected return status: {:?}", other);
+            MountOutcome::Failed(format!("unexpected status: {:?}", other))
         }
-        Ok(MountStatus::Done) => debug!("Mounting of {} was successful", path),
-        _ => error!("Unexpected return status: {:?}", status),
     };
+    reports.lock().unwrap().push(MountReport { path, outcome });
     *mounts_left -= 1;
 
     // Ends the main loop if there are no more mounts left.
@@ -65,16 +139,52 @@ fn user_mount_cb(
     glib::Continue(*mounts_left != 0)
 }
 
-pub fn handle_user_mounts(mounts_file: &str) -> Result<(), AdsysMountError> {
+pub fn handle_user_mounts(mounts_file: &str) -> Result<Vec<MountReport>, AdsysMountError> {
     debug!("Mounting entries listed in {}", mounts_file);
 
     let parsed_entries = parse_entries(mounts_file).map_err(|e| {
         error!("Error when parsing entries: {}", e);
-        AdsysMountError::ParseError
+        e
+    })?;
+
+    Ok(run_loop(
+        parsed_entries,
+        handle_mount,
+        gio::IOErrorEnum::AlreadyMounted,
+    ))
+}
+
+/// Tears down every mount listed in `mounts_file`, unmounting (or ejecting, when the backing
+/// volume supports it) each entry. Mirrors [`handle_user_mounts`].
+pub fn handle_user_unmounts(mounts_file: &str) -> Result<Vec<MountReport>, AdsysMountError> {
+    debug!("Unmounting entries listed in {}", mounts_file);
+
+    let parsed_entries = parse_entries(mounts_file).map_err(|e| {
+        error!("Error when parsing entries: {}", e);
+        e
     })?;
 
+    // A share that is no longer mounted surfaces as `find_enclosing_mount` returning
+    // `NotFound`, not `NotMounted` (which in practice gio never reports here) - this is what
+    // makes unmounting an already-unmounted entry idempotent.
+    Ok(run_loop(
+        parsed_entries,
+        handle_unmount,
+        gio::IOErrorEnum::NotFound,
+    ))
+}
+
+/// Drives `handler` for every entry, pumping a glib main loop until all of them have reported
+/// back, then returns one [`MountReport`] per entry. `ignorable` names the error that marks an
+/// entry as already in the desired state rather than failed (e.g. `AlreadyMounted` when
+/// mounting, `NotFound` when unmounting an already-unmounted share).
+fn run_loop(
+    parsed_entries: Vec<MountEntry>,
+    handler: fn(MountEntry, glib::Sender<Msg>),
+    ignorable: gio::IOErrorEnum,
+) -> Vec<MountReport> {
     if parsed_entries.is_empty() {
-        return Ok(());
+        return Vec::new();
     }
 
     // Setting up the channel used for communication between the mount operations and the main function.
@@ -85,94 +195,206 @@ pub fn handle_user_mounts(mounts_file: &str) -> Result<(), AdsysMountError> {
     let mut mounts_left = parsed_entries.len();
 
     for entry in parsed_entries {
-        handle_mount(entry, tx.clone());
+        handler(entry, tx.clone());
     }
 
     // Sets the main loop glib to be used by the mounts
     let g_loop = glib::MainLoop::new(Some(&g_ctx), false);
 
-    // Creates a mutex to handle the exit status
-    let errors = Arc::new(Mutex::new(Vec::new()));
+    // Creates a mutex to hold the per-entry reports as they come in.
+    let reports = Arc::new(Mutex::new(Vec::new()));
 
     // Attaches the receiver to the main context, along with a closure that is called everytime there is a new message in the channel.
     {
         // Clone shared data for closure capture.
-        let errors = errors.clone();
+        let reports = reports.clone();
         let g_loop = g_loop.clone();
         rx.attach(Some(&g_ctx), move |msg| {
-            user_mount_cb(msg, &errors, &g_loop, &mut mounts_left)
+            user_mount_cb(msg, ignorable, &reports, &g_loop, &mut mounts_left)
         });
     }
 
     g_loop.run();
 
-    // Evaluates the arc content to check if at least one operation failed.
-    let errors = errors.lock().unwrap();
-    if errors.is_empty() {
-        return Ok(());
-    }
-
-    for MountError { path, error } in errors.iter() {
-        warn!("Mount process for {} failed: {}", path, error);
-    }
-
-    // Ensures that the function will not error out if the location was already mounted.
-    if errors
-        .iter()
-        .any(|MountError { error, .. }| !error.matches(gio::IOErrorEnum::AlreadyMounted))
-    {
-        Ok(())
-    } else {
-        Err(AdsysMountError::MountError)
-    }
+    Arc::try_unwrap(reports)
+        .expect("no other references to the reports should remain once the loop has quit")
+        .into_inner()
+        .unwrap()
 }
 
 /// Reads the file and parses the mount points listed in it.
-fn parse_entries(path: &str) -> Result<Vec<MountEntry>, std::io::Error> {
+fn parse_entries(path: &str) -> Result<Vec<MountEntry>, AdsysMountError> {
     debug!("Parsing file {} content", path);
 
     let mut parsed_entries: Vec<MountEntry> = Vec::new();
 
-    // The ? operator tries to unwrap the result and, if there is an error, returns it to the caller of this function.
-    let content = fs::read_to_string(path)?;
+    let content =
+        fs::read_to_string(path).map_err(|e| AdsysMountError::ParseError(e.to_string()))?;
 
     for p in content.lines() {
         if p.is_empty() {
             continue;
         }
 
-        parsed_entries.push(match p.strip_prefix("[anonymous]") {
-            Some(s) => MountEntry {
-                mount_path: s.to_string(),
-                is_anonymous: true,
-            },
-            None => MountEntry {
-                mount_path: p.to_string(),
-                is_anonymous: false,
-            },
-        });
+        parsed_entries.push(parse_entry(p)?);
     }
 
     Ok(parsed_entries)
 }
 
-/// Handles the mount operation to mount the specified entry.
-fn handle_mount(entry: MountEntry, tx: glib::Sender<Msg>) {
-    debug!("Mounting entry {}", entry.mount_path);
+/// Parses a single mounts file line into a [`MountEntry`], pulling the optional
+/// bracketed, comma-separated option list (if any) off the front of the line.
+fn parse_entry(line: &str) -> Result<MountEntry, AdsysMountError> {
+    let Some(rest) = line.strip_prefix('[') else {
+        return Ok(MountEntry {
+            mount_path: line.to_string(),
+            options: MountOptions::default(),
+        });
+    };
 
-    let f = gio::File::for_uri(&entry.mount_path);
+    let (raw_options, uri) = rest
+        .split_once(']')
+        .ok_or_else(|| AdsysMountError::ParseError(line.to_string()))?;
+
+    Ok(MountEntry {
+        mount_path: uri.trim().to_string(),
+        options: parse_options(raw_options)?,
+    })
+}
+
+/// Parses the comma-separated option list found inside the brackets of a mounts file entry,
+/// e.g. `anonymous,readonly,timeout=30`. Unknown keys are reported as a [`AdsysMountError::ParseError`].
+fn parse_options(raw: &str) -> Result<MountOptions, AdsysMountError> {
+    let mut options = MountOptions::default();
+
+    for token in raw.split(',') {
+        match token.split_once('=') {
+            Some(("timeout", value)) => {
+                let secs: u64 = value
+                    .parse()
+                    .map_err(|_| AdsysMountError::ParseError(token.to_string()))?;
+                options.timeout = Some(Duration::from_secs(secs));
+            }
+            Some(("user", value)) => options.username = Some(value.to_string()),
+            Some(("domain", value)) => options.domain = Some(value.to_string()),
+            Some(_) => return Err(AdsysMountError::ParseError(token.to_string())),
+            None => match token {
+                "anonymous" => options.is_anonymous = true,
+                "readonly" => options.read_only = true,
+                _ => return Err(AdsysMountError::ParseError(token.to_string())),
+            },
+        }
+    }
+
+    Ok(options)
+}
+
+/// Returns an error if `entry` requests an option that gio's mount backend has no way to honor,
+/// so callers can refuse the operation instead of silently ignoring the option. Shared by the
+/// mount and session mounting paths; the unmount path does not check options.
+fn check_mount_options_supported(entry: &MountEntry) -> Result<(), glib::Error> {
+    if entry.options.read_only {
+        // GIO's GMountMountFlags has no read-only bit, so there is no way to honor this
+        // option today. Refuse to mount rather than silently handing back a read-write
+        // mount that doesn't match what the admin asked for.
+        warn!(
+            "Read-only mount requested for {} but is not supported by the gio backend, refusing to mount",
+            entry.mount_path
+        );
+        return Err(glib::Error::new(
+            gio::IOErrorEnum::NotSupported,
+            "read-only mounts are not supported by the underlying gio backend",
+        ));
+    }
+
+    Ok(())
+}
 
+/// Builds a `gio::MountOperation` wired up with `entry`'s anonymous flag and its
+/// username/domain/password callback. Shared by the mount, unmount, and session mounting paths.
+fn build_mount_operation(entry: &MountEntry) -> gio::MountOperation {
     let mount_op = gio::MountOperation::new();
 
-    if entry.is_anonymous {
+    if entry.options.is_anonymous {
         debug!("Anonymous mount requested for {}", entry.mount_path);
         mount_op.set_anonymous(true);
     }
 
-    mount_op.connect_ask_password(ask_password_cb);
+    let uri = entry.mount_path.clone();
+    let username = entry.options.username.clone();
+    let domain = entry.options.domain.clone();
+    mount_op.connect_ask_password(move |mount_op, _, _, _, flags| {
+        ask_password_cb(mount_op, flags, username.as_deref(), domain.as_deref(), &uri)
+    });
+
+    mount_op
+}
+
+/// A timeout source armed by [`arm_timeout`]. Once its timer fires, glib has already removed
+/// the source itself, so [`TimeoutGuard::remove`] tracks that and skips calling `g_source_remove`
+/// again on what is by then a stale ID - a double-remove glib logs as a critical and which will
+/// abort the process under `G_DEBUG=fatal-criticals`.
+struct TimeoutGuard {
+    source_id: glib::SourceId,
+    fired: Rc<Cell<bool>>,
+}
+
+impl TimeoutGuard {
+    /// Removes the timeout source, unless it has already fired (in which case glib has already
+    /// removed it on its own).
+    fn remove(self) {
+        if !self.fired.get() {
+            self.source_id.remove();
+        }
+    }
+}
+
+/// Arms a timer that cancels `cancellable` if the operation it guards is still pending once
+/// `entry`'s configured (or default) timeout elapses. Shared by the mount, unmount, and session
+/// mounting paths; the eventual `Cancelled` error is what lets an operation show up as a timeout
+/// to its caller.
+fn arm_timeout(entry: &MountEntry, cancellable: &gio::Cancellable) -> TimeoutGuard {
+    let timeout = entry.options.timeout.unwrap_or(DEFAULT_MOUNT_TIMEOUT);
+    let fired = Rc::new(Cell::new(false));
+
+    let source_id = {
+        let cancellable = cancellable.clone();
+        let path = entry.mount_path.clone();
+        let fired = fired.clone();
+        glib::timeout_add_local(timeout, move || {
+            warn!("Operation on {} timed out after {:?}, cancelling", path, timeout);
+            fired.set(true);
+            cancellable.cancel();
+            glib::Continue(false)
+        })
+    };
+
+    TimeoutGuard { source_id, fired }
+}
+
+/// Handles the mount operation to mount the specified entry.
+fn handle_mount(entry: MountEntry, tx: glib::Sender<Msg>) {
+    debug!("Mounting entry {}", entry.mount_path);
+
+    if let Err(error) = check_mount_options_supported(&entry) {
+        let msg = Msg {
+            path: entry.mount_path,
+            status: Err(error),
+        };
+        if let Err(e) = tx.send(msg) {
+            error!("Failed to send message in the channel: {}", e)
+        };
+        return;
+    }
+
+    let f = gio::File::for_uri(&entry.mount_path);
+    let cancellable = gio::Cancellable::new();
+    let mount_op = build_mount_operation(&entry);
+    let timeout_source = arm_timeout(&entry, &cancellable);
 
     // Callback invoked by gio after setting up the mount.
     let mount_handled_cb = move |r: Result<(), glib::Error>| {
+        timeout_source.remove();
         let msg = Msg {
             path: entry.mount_path,
             status: r.map(|_| MountStatus::Done),
@@ -185,19 +407,96 @@ fn handle_mount(entry: MountEntry, tx: glib::Sender<Msg>) {
     f.mount_enclosing_volume(
         gio::MountMountFlags::NONE,
         Some(&mount_op),
-        gio::Cancellable::NONE,
+        Some(&cancellable),
         mount_handled_cb,
     );
 }
 
-/// Callback that is invoked by gio when prompted for password.
+/// Handles the unmount (or eject, for ejectable volumes) operation for the specified entry.
+fn handle_unmount(entry: MountEntry, tx: glib::Sender<Msg>) {
+    debug!("Unmounting entry {}", entry.mount_path);
+
+    let f = gio::File::for_uri(&entry.mount_path);
+    let cancellable = gio::Cancellable::new();
+
+    let mount = match f.find_enclosing_mount(gio::Cancellable::NONE) {
+        Ok(mount) => mount,
+        Err(error) => {
+            let msg = Msg {
+                path: entry.mount_path,
+                status: Err(error),
+            };
+            if let Err(e) = tx.send(msg) {
+                error!("Failed to send message in the channel: {}", e)
+            };
+            return;
+        }
+    };
+
+    let mount_op = build_mount_operation(&entry);
+    let timeout_source = arm_timeout(&entry, &cancellable);
+
+    // Callback invoked by gio after the unmount or eject operation completes.
+    let unmount_handled_cb = move |r: Result<(), glib::Error>| {
+        timeout_source.remove();
+        let msg = Msg {
+            path: entry.mount_path,
+            status: r.map(|_| MountStatus::Done),
+        };
+        if let Err(e) = tx.send(msg) {
+            error!("Failed to send message in the channel: {}", e)
+        };
+    };
+
+    if mount.can_eject() {
+        mount.eject_with_operation(
+            gio::MountUnmountFlags::NONE,
+            Some(&mount_op),
+            Some(&cancellable),
+            unmount_handled_cb,
+        );
+        return;
+    }
+
+    mount.unmount_with_operation(
+        gio::MountUnmountFlags::NONE,
+        Some(&mount_op),
+        Some(&cancellable),
+        unmount_handled_cb,
+    );
+}
+
+/// Callback that is invoked by gio when prompted for password. `username`/`domain` are the
+/// credentials configured for the entry being mounted, if any; `uri` identifies it for the
+/// purposes of looking up its secret.
 fn ask_password_cb(
     mount_op: &gio::MountOperation,
-    _: &str,
-    _: &str,
-    _: &str,
     flags: gio::AskPasswordFlags,
+    username: Option<&str>,
+    domain: Option<&str>,
+    uri: &str,
 ) {
+    if username.is_some() || domain.is_some() {
+        if let Some(username) = username.filter(|_| flags.contains(gio::AskPasswordFlags::NEED_USERNAME)) {
+            mount_op.set_username(username);
+        }
+        if let Some(domain) = domain.filter(|_| flags.contains(gio::AskPasswordFlags::NEED_DOMAIN)) {
+            mount_op.set_domain(domain);
+        }
+        if flags.contains(gio::AskPasswordFlags::NEED_PASSWORD) {
+            match fetch_secret(uri, username, domain) {
+                Some(secret) => mount_op.set_password(&secret),
+                None => {
+                    warn!("No credentials available for {}", uri);
+                    mount_op.reply(gio::MountOperationResult::Aborted);
+                    return;
+                }
+            }
+        }
+        mount_op.reply(gio::MountOperationResult::Handled);
+        return;
+    }
+
     if mount_op.is_anonymous() && flags.contains(gio::AskPasswordFlags::ANONYMOUS_SUPPORTED) {
         // Unsafe block is needed for data and set_data implementations in glib.
         unsafe {
@@ -227,5 +526,56 @@ fn ask_password_cb(
     mount_op.reply(gio::MountOperationResult::Aborted);
 }
 
+/// Reads the password for `username`/`domain` at `uri` from the file descriptor named by the
+/// `ADSYS_MOUNT_PASSWORD_FD` environment variable, if set.
+///
+/// Keeping the secret in a descriptor handed to us out-of-band (rather than in the mounts file
+/// or the environment itself) avoids it ever touching disk or `/proc/<pid>/environ`. A
+/// libsecret/keyring lookup keyed by `uri` would be a natural place to extend this from.
+///
+/// The descriptor is read exactly once and the secret cached for the lifetime of the process:
+/// wrapping a raw fd in a `fs::File` takes ownership of it and closes it when that `File`
+/// drops, so a second credentialed entry (or a second password prompt for the same entry) would
+/// otherwise find the descriptor already closed and silently fail to authenticate. This also
+/// means the descriptor can only ever supply one secret for one `username`/`domain` pair per
+/// process: whichever pair asks first "claims" it, and a differently-credentialed entry asking
+/// afterward is refused rather than silently handed a mismatched secret.
+fn fetch_secret(uri: &str, username: Option<&str>, domain: Option<&str>) -> Option<String> {
+    type Claim = (Option<String>, Option<String>, Option<String>);
+    static CLAIM: OnceLock<Claim> = OnceLock::new();
+
+    let (claimed_username, claimed_domain, secret) = CLAIM.get_or_init(|| {
+        let secret = (|| {
+            let fd: i32 = std::env::var("ADSYS_MOUNT_PASSWORD_FD").ok()?.parse().ok()?;
+
+            debug!("Reading credentials for {} from fd {}", uri, fd);
+
+            let mut secret = String::new();
+            // SAFETY: the descriptor is expected to have been opened by our caller for this
+            // sole purpose and handed to us via the environment; we take ownership of it here.
+            let mut file = unsafe { fs::File::from_raw_fd(fd) };
+            file.read_to_string(&mut secret).ok()?;
+
+            Some(secret.trim_end().to_string())
+        })();
+
+        (username.map(str::to_string), domain.map(str::to_string), secret)
+    });
+
+    if claimed_username.as_deref() != username || claimed_domain.as_deref() != domain {
+        warn!(
+            "Not handing out credentials for {} (user={:?}, domain={:?}): the single secret for \
+             this process was already claimed by a different user/domain pair (user={:?}, \
+             domain={:?})",
+            uri, username, domain, claimed_username, claimed_domain
+        );
+        return None;
+    }
+
+    secret.clone()
+}
+
+#[cfg(test)]
 mod test;
+#[cfg(test)]
 mod test_utils;
@@ -0,0 +1,237 @@
+use std::sync::{Arc, Mutex};
+
+use gio::traits::{FileExt, MountExt, VolumeMonitorExt};
+use log::{debug, error, warn};
+
+use crate::{
+    arm_timeout, build_mount_operation, check_mount_options_supported, parse_entries,
+    AdsysMountError, MountEntry,
+};
+
+// Avoids pulling in the `libc` crate for two well-known, never-changing signal numbers.
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+
+/// Maximum number of times a session will silently try to remount a share that gets
+/// disconnected from underneath it before giving up and just logging the loss.
+const DEFAULT_MAX_REMOUNT_ATTEMPTS: u32 = 3;
+
+/// An entry a [`MountSession`] is responsible for, along with how many remount attempts it has
+/// left if its volume goes away.
+struct TrackedMount {
+    entry: MountEntry,
+    file: gio::File,
+    remaining_attempts: u32,
+}
+
+/// A long-running handle returned by [`handle_user_mounts_daemon`]. Owns the main loop driving
+/// the session as well as the set of `gio::File`s it is responsible for tearing down.
+pub struct MountSession {
+    main_loop: glib::MainLoop,
+    tracked: Arc<Mutex<Vec<TrackedMount>>>,
+    // Kept alive for as long as the session is, so its `mount-removed` signal stays connected.
+    _volume_monitor: gio::VolumeMonitor,
+}
+
+impl MountSession {
+    /// Runs the session's main loop. Blocks the calling thread, watching for volumes going
+    /// away and retrying them, until a SIGINT/SIGTERM tells it to unmount everything it owns
+    /// and quit.
+    pub fn run(&self) {
+        self.main_loop.run();
+    }
+}
+
+/// Starts a daemon-mode mount session: mounts every entry listed in `mounts_file`, then keeps
+/// the returned [`MountSession`]'s main loop watching for those shares being disconnected
+/// (attempting a bounded number of automatic remounts) until a SIGINT/SIGTERM unmounts
+/// everything and quits. Mounting happens as the session's main loop is driven via
+/// [`MountSession::run`], not before this function returns.
+pub fn handle_user_mounts_daemon(mounts_file: &str) -> Result<MountSession, AdsysMountError> {
+    debug!("Starting a mount session for entries listed in {}", mounts_file);
+
+    let parsed_entries = parse_entries(mounts_file).map_err(|e| {
+        error!("Error when parsing entries: {}", e);
+        e
+    })?;
+
+    let g_ctx = glib::MainContext::default();
+    let main_loop = glib::MainLoop::new(Some(&g_ctx), false);
+    let tracked: Arc<Mutex<Vec<TrackedMount>>> = Arc::new(Mutex::new(Vec::new()));
+
+    for entry in parsed_entries {
+        start_mount(entry, tracked.clone(), DEFAULT_MAX_REMOUNT_ATTEMPTS);
+    }
+
+    let volume_monitor = gio::VolumeMonitor::get();
+    {
+        let tracked = tracked.clone();
+        volume_monitor.connect_mount_removed(move |_, removed| {
+            on_mount_removed(&tracked, removed);
+        });
+    }
+
+    for signum in [SIGINT, SIGTERM] {
+        let tracked = tracked.clone();
+        let main_loop = main_loop.clone();
+        glib::unix_signal_add(signum, move || {
+            debug!("Received termination signal {}, tearing down the session", signum);
+            unmount_all(&tracked, &main_loop);
+            glib::Continue(false)
+        });
+    }
+
+    Ok(MountSession {
+        main_loop,
+        tracked,
+        _volume_monitor: volume_monitor,
+    })
+}
+
+/// Mounts `entry` and, on success, starts tracking it under `tracked` with `remaining_attempts`
+/// automatic remounts left. Mirrors [`crate::handle_mount`], but keeps hold of the `gio::File`
+/// instead of reporting back over a channel, since a session outlives any single mount attempt.
+///
+/// A failed attempt here is not itself retried: `remaining_attempts` is a budget for
+/// [`on_mount_removed`] to spend on a share that was mounted successfully and later disconnected
+/// from underneath the session, not a generic retry-on-any-error policy - an unreachable host or
+/// a rejected login would otherwise get hammered with back-to-back attempts on every daemon
+/// start.
+fn start_mount(entry: MountEntry, tracked: Arc<Mutex<Vec<TrackedMount>>>, remaining_attempts: u32) {
+    debug!("Mounting entry {} for the session", entry.mount_path);
+
+    if let Err(error) = check_mount_options_supported(&entry) {
+        warn!("Not tracking {} in the session: {}", entry.mount_path, error);
+        return;
+    }
+
+    let f = gio::File::for_uri(&entry.mount_path);
+    let cancellable = gio::Cancellable::new();
+    let mount_op = build_mount_operation(&entry);
+    let timeout_source = arm_timeout(&entry, &cancellable);
+
+    let path = entry.mount_path.clone();
+    let file_for_session = f.clone();
+    f.mount_enclosing_volume(
+        gio::MountMountFlags::NONE,
+        Some(&mount_op),
+        Some(&cancellable),
+        move |r| {
+            timeout_source.remove();
+            match r {
+                Ok(()) => debug!("Mounting of {} was successful", path),
+                Err(e) if e.matches(gio::IOErrorEnum::AlreadyMounted) => {
+                    debug!("{} was already mounted", path);
+                }
+                Err(e) => {
+                    warn!("Failed to mount {} in session: {}", path, e);
+                    return;
+                }
+            }
+            tracked.lock().unwrap().push(TrackedMount {
+                entry,
+                file: file_for_session,
+                remaining_attempts,
+            });
+        },
+    );
+}
+
+/// Whether a tracked entry's URI is the removed mount's root, or a path under it. An entry can
+/// mount the volume's root or some path under it, so this matches either exactly or by prefix
+/// rather than requiring the tracked file's URI to equal the removed mount's root.
+pub(crate) fn uri_matches_removed_mount(file_uri: &str, removed_uri: &str) -> bool {
+    let removed_prefix = format!("{}/", removed_uri.trim_end_matches('/'));
+    file_uri == removed_uri || file_uri.starts_with(removed_prefix.as_str())
+}
+
+/// Called when the system reports a mount going away; if it is one of ours, retries it as long
+/// as it still has remount attempts left.
+fn on_mount_removed(tracked: &Arc<Mutex<Vec<TrackedMount>>>, removed: &gio::Mount) {
+    let Some(removed_uri) = removed.root().map(|f| f.uri().to_string()) else {
+        return;
+    };
+
+    let mut guard = tracked.lock().unwrap();
+    let Some(position) = guard
+        .iter()
+        .position(|t| uri_matches_removed_mount(t.file.uri().as_str(), &removed_uri))
+    else {
+        return;
+    };
+    let tracked_mount = guard.remove(position);
+    drop(guard);
+
+    if tracked_mount.remaining_attempts == 0 {
+        warn!(
+            "{} was disconnected and is out of remount attempts",
+            removed_uri
+        );
+        return;
+    }
+
+    let remaining_attempts = tracked_mount.remaining_attempts - 1;
+    warn!(
+        "{} was disconnected, attempting to remount ({} attempts left)",
+        removed_uri, remaining_attempts
+    );
+    start_mount(tracked_mount.entry, tracked.clone(), remaining_attempts);
+}
+
+/// Unmounts every share the session still owns, then quits `main_loop` once they have all
+/// reported back (rather than right away, since the unmount calls just issued are themselves
+/// async and still need the main loop running to be dispatched and completed).
+fn unmount_all(tracked: &Arc<Mutex<Vec<TrackedMount>>>, main_loop: &glib::MainLoop) {
+    let to_unmount: Vec<TrackedMount> = tracked.lock().unwrap().drain(..).collect();
+
+    if to_unmount.is_empty() {
+        main_loop.quit();
+        return;
+    }
+
+    let pending = Arc::new(Mutex::new(to_unmount.len()));
+    for TrackedMount { entry, file, .. } in to_unmount {
+        debug!("Unmounting {} as the session shuts down", entry.mount_path);
+
+        let Ok(mount) = file.find_enclosing_mount(gio::Cancellable::NONE) else {
+            let mut pending = pending.lock().unwrap();
+            *pending -= 1;
+            if *pending == 0 {
+                main_loop.quit();
+            }
+            continue;
+        };
+
+        let mount_op = gio::MountOperation::new();
+        let path = entry.mount_path;
+        let pending = pending.clone();
+        let main_loop = main_loop.clone();
+        let unmount_handled_cb = move |r: Result<(), glib::Error>| {
+            if let Err(e) = r {
+                warn!("Failed to unmount {} during session shutdown: {}", path, e);
+            }
+            let mut pending = pending.lock().unwrap();
+            *pending -= 1;
+            if *pending == 0 {
+                main_loop.quit();
+            }
+        };
+
+        if mount.can_eject() {
+            mount.eject_with_operation(
+                gio::MountUnmountFlags::NONE,
+                Some(&mount_op),
+                gio::Cancellable::NONE,
+                unmount_handled_cb,
+            );
+            continue;
+        }
+
+        mount.unmount_with_operation(
+            gio::MountUnmountFlags::NONE,
+            Some(&mount_op),
+            gio::Cancellable::NONE,
+            unmount_handled_cb,
+        );
+    }
+}
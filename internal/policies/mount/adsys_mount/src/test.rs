@@ -0,0 +1,292 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use super::*;
+use crate::session::uri_matches_removed_mount;
+use crate::test_utils::TempMountsFile;
+
+#[test]
+fn parse_entry_without_options() {
+    let entry = parse_entry("smb://server/share").unwrap();
+
+    assert_eq!(
+        entry,
+        MountEntry {
+            mount_path: "smb://server/share".to_string(),
+            options: MountOptions::default(),
+        }
+    );
+}
+
+#[test]
+fn parse_entry_with_options() {
+    let entry = parse_entry("[anonymous,readonly,timeout=5,user=alice,domain=CORP] smb://server/share").unwrap();
+
+    assert_eq!(
+        entry,
+        MountEntry {
+            mount_path: "smb://server/share".to_string(),
+            options: MountOptions {
+                is_anonymous: true,
+                read_only: true,
+                timeout: Some(Duration::from_secs(5)),
+                username: Some("alice".to_string()),
+                domain: Some("CORP".to_string()),
+            },
+        }
+    );
+}
+
+#[test]
+fn parse_entry_trims_whitespace_after_options() {
+    let entry = parse_entry("[anonymous]   smb://server/share").unwrap();
+
+    assert_eq!(entry.mount_path, "smb://server/share");
+}
+
+#[test]
+fn parse_entry_with_unterminated_bracket_is_an_error() {
+    assert!(matches!(
+        parse_entry("[anonymous smb://server/share"),
+        Err(AdsysMountError::ParseError(_))
+    ));
+}
+
+#[test]
+fn parse_options_rejects_unknown_key() {
+    assert!(matches!(
+        parse_options("bogus"),
+        Err(AdsysMountError::ParseError(token)) if token == "bogus"
+    ));
+}
+
+#[test]
+fn parse_options_rejects_unknown_key_with_value() {
+    assert!(matches!(
+        parse_options("bogus=1"),
+        Err(AdsysMountError::ParseError(token)) if token == "bogus=1"
+    ));
+}
+
+#[test]
+fn parse_options_rejects_non_numeric_timeout() {
+    assert!(matches!(
+        parse_options("timeout=soon"),
+        Err(AdsysMountError::ParseError(token)) if token == "timeout=soon"
+    ));
+}
+
+#[test]
+fn parse_entries_skips_empty_lines() {
+    let file = TempMountsFile::new("smb://server/a\n\nsmb://server/b\n");
+
+    let entries = parse_entries(file.path()).unwrap();
+
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].mount_path, "smb://server/a");
+    assert_eq!(entries[1].mount_path, "smb://server/b");
+}
+
+#[test]
+fn parse_entries_missing_file_is_an_error() {
+    assert!(matches!(
+        parse_entries("/nonexistent/adsys_mount_test_missing.txt"),
+        Err(AdsysMountError::ParseError(_))
+    ));
+}
+
+#[test]
+fn check_mount_options_supported_rejects_read_only() {
+    let entry = MountEntry {
+        mount_path: "smb://server/share".to_string(),
+        options: MountOptions {
+            read_only: true,
+            ..MountOptions::default()
+        },
+    };
+
+    let error = check_mount_options_supported(&entry).unwrap_err();
+    assert!(error.matches(gio::IOErrorEnum::NotSupported));
+}
+
+#[test]
+fn check_mount_options_supported_allows_read_write() {
+    let entry = MountEntry {
+        mount_path: "smb://server/share".to_string(),
+        options: MountOptions::default(),
+    };
+
+    assert!(check_mount_options_supported(&entry).is_ok());
+}
+
+#[test]
+fn mount_report_is_ok_for_mounted_and_already_mounted() {
+    let mounted = MountReport {
+        path: "smb://server/share".to_string(),
+        outcome: MountOutcome::Mounted,
+    };
+    let already_mounted = MountReport {
+        path: "smb://server/share".to_string(),
+        outcome: MountOutcome::AlreadyMounted,
+    };
+
+    assert!(mounted.is_ok());
+    assert!(already_mounted.is_ok());
+}
+
+#[test]
+fn mount_report_is_not_ok_for_failed_or_timed_out() {
+    let failed = MountReport {
+        path: "smb://server/share".to_string(),
+        outcome: MountOutcome::Failed("boom".to_string()),
+    };
+    let timed_out = MountReport {
+        path: "smb://server/share".to_string(),
+        outcome: MountOutcome::TimedOut,
+    };
+
+    assert!(!failed.is_ok());
+    assert!(!timed_out.is_ok());
+}
+
+#[test]
+fn summarize_ok_when_every_report_is_ok() {
+    let reports = vec![
+        MountReport {
+            path: "smb://server/a".to_string(),
+            outcome: MountOutcome::Mounted,
+        },
+        MountReport {
+            path: "smb://server/b".to_string(),
+            outcome: MountOutcome::AlreadyMounted,
+        },
+    ];
+
+    assert!(summarize(&reports).is_ok());
+}
+
+#[test]
+fn summarize_errors_when_any_report_failed_or_timed_out() {
+    let reports = vec![
+        MountReport {
+            path: "smb://server/a".to_string(),
+            outcome: MountOutcome::Mounted,
+        },
+        MountReport {
+            path: "smb://server/b".to_string(),
+            outcome: MountOutcome::TimedOut,
+        },
+    ];
+
+    assert!(matches!(
+        summarize(&reports),
+        Err(AdsysMountError::MountError)
+    ));
+}
+
+#[test]
+fn uri_matches_removed_mount_matches_the_root_exactly() {
+    assert!(uri_matches_removed_mount(
+        "smb://server/share",
+        "smb://server/share"
+    ));
+}
+
+#[test]
+fn uri_matches_removed_mount_matches_a_subpath_of_the_root() {
+    assert!(uri_matches_removed_mount(
+        "smb://server/share/subdir",
+        "smb://server/share"
+    ));
+}
+
+#[test]
+fn uri_matches_removed_mount_ignores_an_unrelated_uri() {
+    assert!(!uri_matches_removed_mount(
+        "smb://server/other-share",
+        "smb://server/share"
+    ));
+}
+
+#[test]
+fn uri_matches_removed_mount_does_not_match_a_sibling_with_a_shared_prefix() {
+    assert!(!uri_matches_removed_mount(
+        "smb://server/share-backup",
+        "smb://server/share"
+    ));
+}
+
+#[test]
+fn ask_password_cb_skips_set_domain_without_a_configured_domain() {
+    let mount_op = gio::MountOperation::new();
+
+    ask_password_cb(
+        &mount_op,
+        gio::AskPasswordFlags::NEED_DOMAIN,
+        None,
+        None,
+        "smb://server/share",
+    );
+
+    assert_eq!(mount_op.domain(), "");
+}
+
+#[test]
+fn ask_password_cb_sets_username_when_needed_and_configured() {
+    let mount_op = gio::MountOperation::new();
+
+    ask_password_cb(
+        &mount_op,
+        gio::AskPasswordFlags::NEED_USERNAME,
+        Some("alice"),
+        None,
+        "smb://server/share",
+    );
+
+    assert_eq!(mount_op.username(), "alice");
+}
+
+#[test]
+fn ask_password_cb_aborts_when_password_needed_but_unavailable() {
+    let mount_op = gio::MountOperation::new();
+    let result = Rc::new(Cell::new(None));
+    {
+        let result = result.clone();
+        mount_op.connect_reply(move |_, r| result.set(Some(r)));
+    }
+
+    // No `ADSYS_MOUNT_PASSWORD_FD` is set in the test environment, so `fetch_secret` has nothing
+    // to hand back here.
+    ask_password_cb(
+        &mount_op,
+        gio::AskPasswordFlags::NEED_PASSWORD,
+        Some("alice"),
+        None,
+        "smb://server/share",
+    );
+
+    assert_eq!(result.get(), Some(gio::MountOperationResult::Aborted));
+}
+
+#[test]
+fn ask_password_cb_prefers_configured_credentials_over_anonymous() {
+    let mount_op = gio::MountOperation::new();
+    mount_op.set_anonymous(true);
+    let result = Rc::new(Cell::new(None));
+    {
+        let result = result.clone();
+        mount_op.connect_reply(move |_, r| result.set(Some(r)));
+    }
+
+    ask_password_cb(
+        &mount_op,
+        gio::AskPasswordFlags::NEED_USERNAME | gio::AskPasswordFlags::ANONYMOUS_SUPPORTED,
+        Some("alice"),
+        None,
+        "smb://server/share",
+    );
+
+    assert_eq!(mount_op.username(), "alice");
+    assert_eq!(result.get(), Some(gio::MountOperationResult::Handled));
+}
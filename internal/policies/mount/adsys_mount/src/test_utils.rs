@@ -0,0 +1,32 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A mounts file written to the system temp directory for the duration of a test, removed
+/// automatically when dropped.
+pub(crate) struct TempMountsFile {
+    path: PathBuf,
+}
+
+impl TempMountsFile {
+    /// Writes `content` to a uniquely-named file under the system temp directory.
+    pub(crate) fn new(content: &str) -> Self {
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let mut path = std::env::temp_dir();
+        path.push(format!("adsys_mount_test_{}_{}.txt", std::process::id(), id));
+        fs::write(&path, content).expect("failed to write test mounts file");
+        TempMountsFile { path }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        self.path.to_str().expect("temp path should be valid UTF-8")
+    }
+}
+
+impl Drop for TempMountsFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}